@@ -0,0 +1,225 @@
+use std::collections::HashMap;
+
+use chrono::{TimeZone, Utc};
+use tokio_postgres::Transaction;
+
+/// Candle granularities rolled up from the raw `price_per_kwh` stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Granularity {
+    OneMinute,
+    FiveMinute,
+    OneHour,
+}
+
+impl Granularity {
+    pub const ALL: [Granularity; 3] = [
+        Granularity::OneMinute,
+        Granularity::FiveMinute,
+        Granularity::OneHour,
+    ];
+
+    fn seconds(&self) -> i64 {
+        match self {
+            Granularity::OneMinute => 60,
+            Granularity::FiveMinute => 5 * 60,
+            Granularity::OneHour => 60 * 60,
+        }
+    }
+
+    /// Floors a unix timestamp (seconds) to the start of this granularity's
+    /// bucket.
+    fn bucket_start(&self, timestamp: i64) -> i64 {
+        let secs = self.seconds();
+        timestamp - timestamp.rem_euclid(secs)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Granularity::OneMinute => "1m",
+            Granularity::FiveMinute => "5m",
+            Granularity::OneHour => "1h",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Candle {
+    bucket_start: i64,
+    open: f64,
+    high: f64,
+    low: f64,
+    close: f64,
+    volume: f64,
+}
+
+impl Candle {
+    fn new(bucket_start: i64, price: f64, kwh_usage: f64) -> Self {
+        Self {
+            bucket_start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: kwh_usage,
+        }
+    }
+
+    fn update(&mut self, price: f64, kwh_usage: f64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += kwh_usage;
+    }
+}
+
+/// Rolls the per-event `price_per_kwh` stream into OHLC candles and
+/// upserts each one into the `price_candles` hypertable as soon as an
+/// incoming event crosses into the next bucket.
+pub struct CandleAggregator {
+    candles: HashMap<(Granularity, i64), Candle>,
+}
+
+impl CandleAggregator {
+    pub fn new() -> Self {
+        Self {
+            candles: HashMap::new(),
+        }
+    }
+
+    /// Folds a new sample into the in-progress candle for every
+    /// granularity, flushing whichever bucket it has rolled past first.
+    ///
+    /// Kafka gives no ordering guarantee across partitions/cars, so a
+    /// sample can arrive for a bucket older than the one currently open
+    /// for its granularity - that bucket has likely already been flushed.
+    /// Rather than open (and then blindly overwrite) a fresh single-sample
+    /// candle for it, such late samples are merged straight into the
+    /// existing DB row.
+    pub async fn ingest(
+        &mut self,
+        txn: &Transaction<'_>,
+        timestamp: i64,
+        price_per_kwh: f64,
+        kwh_usage: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for granularity in Granularity::ALL {
+            let bucket_start = granularity.bucket_start(timestamp);
+            let key = (granularity, bucket_start);
+
+            if let Some(candle) = self.candles.get_mut(&key) {
+                candle.update(price_per_kwh, kwh_usage);
+                continue;
+            }
+
+            let current_bucket_start = self
+                .candles
+                .keys()
+                .filter(|(g, _)| *g == granularity)
+                .map(|(_, bucket)| *bucket)
+                .max();
+
+            if current_bucket_start.is_some_and(|current| bucket_start < current) {
+                self.merge_late_sample(txn, granularity, bucket_start, price_per_kwh, kwh_usage)
+                    .await?;
+                continue;
+            }
+
+            // The event rolled into a new, later bucket for this
+            // granularity - flush whatever buckets it has rolled past
+            // before starting the new one.
+            let stale_keys: Vec<(Granularity, i64)> = self
+                .candles
+                .keys()
+                .filter(|(g, bucket)| *g == granularity && *bucket < bucket_start)
+                .copied()
+                .collect();
+
+            for stale_key in stale_keys {
+                if let Some(stale_candle) = self.candles.remove(&stale_key) {
+                    self.flush(txn, granularity, &stale_candle).await?;
+                }
+            }
+
+            self.candles
+                .insert(key, Candle::new(bucket_start, price_per_kwh, kwh_usage));
+        }
+
+        Ok(())
+    }
+
+    async fn flush(
+        &self,
+        txn: &Transaction<'_>,
+        granularity: Granularity,
+        candle: &Candle,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let time = Utc
+            .timestamp_opt(candle.bucket_start, 0)
+            .single()
+            .ok_or("invalid candle bucket_start")?;
+
+        txn
+            .execute(
+                "INSERT INTO price_candles (time, granularity, open, high, low, close, volume)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (time, granularity) DO UPDATE SET
+                     open = EXCLUDED.open,
+                     high = EXCLUDED.high,
+                     low = EXCLUDED.low,
+                     close = EXCLUDED.close,
+                     volume = EXCLUDED.volume",
+                &[
+                    &time,
+                    &granularity.label(),
+                    &candle.open,
+                    &candle.high,
+                    &candle.low,
+                    &candle.close,
+                    &candle.volume,
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    /// Merges a sample for a bucket that has already closed (and likely
+    /// already been flushed) into the existing `price_candles` row,
+    /// instead of overwriting its open/high/low/close with a fresh
+    /// single-sample candle. `open`/`close` are left to whichever row
+    /// already holds them, since arrival order no longer reflects event
+    /// order once a bucket has closed.
+    async fn merge_late_sample(
+        &self,
+        txn: &Transaction<'_>,
+        granularity: Granularity,
+        bucket_start: i64,
+        price_per_kwh: f64,
+        kwh_usage: f64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let time = Utc
+            .timestamp_opt(bucket_start, 0)
+            .single()
+            .ok_or("invalid candle bucket_start")?;
+
+        txn
+            .execute(
+                "INSERT INTO price_candles (time, granularity, open, high, low, close, volume)
+                 VALUES ($1, $2, $3, $3, $3, $3, $4)
+                 ON CONFLICT (time, granularity) DO UPDATE SET
+                     high = GREATEST(price_candles.high, EXCLUDED.high),
+                     low = LEAST(price_candles.low, EXCLUDED.low),
+                     volume = price_candles.volume + EXCLUDED.volume",
+                &[&time, &granularity.label(), &price_per_kwh, &kwh_usage],
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+impl Default for CandleAggregator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
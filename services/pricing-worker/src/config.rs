@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+/// Runtime configuration, loaded from environment variables (populated
+/// from a local `.env` file in development via `dotenv`) so the same
+/// binary runs unmodified across dev/staging/prod.
+pub struct Config {
+    pub kafka_broker: String,
+    pub kafka_topic: String,
+    pub kafka_group_id: String,
+    pub kafka_output_topic: String,
+    pub db_connection: String,
+    pub mock_grid_url: String,
+    pub stations_config_path: String,
+    pub db_pool_size: u32,
+    pub batch_max_size: usize,
+    pub batch_max_interval: Duration,
+    pub metrics_addr: String,
+    pub metrics_log_interval: Duration,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        dotenv::dotenv().ok();
+
+        Self {
+            kafka_broker: env_or("KAFKA_BROKER", "localhost:19092"),
+            kafka_topic: env_or("KAFKA_TOPIC", "fleet-events"),
+            kafka_group_id: env_or("KAFKA_GROUP_ID", "pricing-worker-group"),
+            kafka_output_topic: env_or("KAFKA_OUTPUT_TOPIC", "priced-sessions"),
+            db_connection: env_or(
+                "DB_CONNECTION",
+                "host=localhost user=greenlane password=greenlane_password dbname=greenlane",
+            ),
+            mock_grid_url: env_or("MOCK_GRID_URL", "http://localhost:8081/api/pricing"),
+            stations_config_path: env_or("STATIONS_CONFIG_PATH", "stations.json"),
+            db_pool_size: env_parsed("DB_POOL_SIZE", 10),
+            batch_max_size: env_parsed("BATCH_MAX_SIZE", 25),
+            batch_max_interval: Duration::from_millis(env_parsed("BATCH_MAX_INTERVAL_MS", 5_000)),
+            metrics_addr: env_or("METRICS_ADDR", "0.0.0.0:9100"),
+            metrics_log_interval: Duration::from_secs(env_parsed("METRICS_LOG_INTERVAL_SECS", 30)),
+        }
+    }
+}
+
+fn env_or(key: &str, default: &str) -> String {
+    std::env::var(key).unwrap_or_else(|_| default.to_string())
+}
+
+fn env_parsed<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
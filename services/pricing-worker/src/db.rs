@@ -0,0 +1,17 @@
+use bb8::Pool;
+use bb8_postgres::PostgresConnectionManager;
+use tokio_postgres::NoTls;
+
+/// Shared pool of Postgres connections, so concurrent processing tasks
+/// don't serialize on a single socket and a dropped connection doesn't
+/// take the whole worker down with it.
+pub type DbPool = Pool<PostgresConnectionManager<NoTls>>;
+
+pub async fn build_pool(
+    db_connection: &str,
+    pool_size: u32,
+) -> Result<DbPool, Box<dyn std::error::Error>> {
+    let manager = PostgresConnectionManager::new_from_stringlike(db_connection, NoTls)?;
+    let pool = Pool::builder().max_size(pool_size).build(manager).await?;
+    Ok(pool)
+}
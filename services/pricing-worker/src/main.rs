@@ -1,17 +1,31 @@
-use chrono::Utc;
+mod candles;
+mod config;
+mod db;
+mod metrics;
+mod oracle;
+mod producer;
+mod stations;
+
+use chrono::{DateTime, TimeZone, Utc};
 use log::{error, info};
 use rdkafka::config::ClientConfig;
-use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
 use rdkafka::message::Message;
-use serde::{Deserialize, Serialize};
-use std::time::Duration;
-use tokio_postgres::NoTls;
+use rdkafka::producer::FutureProducer;
+use rdkafka::{Offset, TopicPartitionList};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_postgres::types::ToSql;
 
-const KAFKA_BROKER: &str = "localhost:19092";
-const KAFKA_TOPIC: &str = "fleet-events";
-const KAFKA_GROUP_ID: &str = "pricing-worker-group";
-const DB_CONNECTION: &str = "host=localhost user=greenlane password=greenlane_password dbname=greenlane";
-const MOCK_GRID_URL: &str = "http://localhost:8081/api/pricing";
+use candles::CandleAggregator;
+use config::Config;
+use db::DbPool;
+use metrics::LatencyMetrics;
+use oracle::{FallbackOracle, FlatRateOracle, MockGridOracle, PriceResponse, PricingOracle, TimeOfUseOracle};
+use producer::PricedSessionEvent;
+use stations::{CarSessionTracker, StationRegistry};
 
 #[derive(Debug, Deserialize)]
 struct TelemetryEvent {
@@ -24,51 +38,89 @@ struct TelemetryEvent {
     event_type: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct PriceResponse {
-    timestamp: i64,
-    price_per_kwh: f64,
-    grid_load: String,
-    energy_source: String,
-    hour: i32,
+/// A decoded event together with the price resolved for it and the
+/// session details modeled for it, held in memory until its batch is
+/// flushed to TimescaleDB.
+struct PendingEvent {
+    event: TelemetryEvent,
+    price_info: PriceResponse,
+    station_id: String,
+    kwh_usage: f64,
+}
+
+/// Builds the oracle chain the main loop will query: the mock grid
+/// endpoint first, falling back to a time-of-use estimate, and finally a
+/// flat operator-configured rate if everything else is unavailable.
+fn build_oracle(http_client: reqwest::Client, mock_grid_url: &str) -> Box<dyn PricingOracle> {
+    Box::new(FallbackOracle::new(vec![
+        Box::new(MockGridOracle::new(http_client, mock_grid_url)),
+        Box::new(TimeOfUseOracle::new()),
+        Box::new(FlatRateOracle::from_env(0.15)),
+    ]))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
 
-    info!("🦀 GreenLane Pricing Worker starting...");
-
-    // Connect to PostgreSQL/TimescaleDB
-    let (client, connection) = tokio_postgres::connect(DB_CONNECTION, NoTls).await?;
+    let cfg = Config::from_env();
 
-    tokio::spawn(async move {
-        if let Err(e) = connection.await {
-            error!("PostgreSQL connection error: {}", e);
-        }
-    });
+    info!("🦀 GreenLane Pricing Worker starting...");
 
-    info!("✅ Connected to TimescaleDB");
+    // Connect to PostgreSQL/TimescaleDB through a shared pool, so
+    // concurrent tasks aren't serialized on one socket and a dropped
+    // connection gets replaced instead of taking the worker down.
+    let pool = db::build_pool(&cfg.db_connection, cfg.db_pool_size).await?;
+    info!("✅ Connected to TimescaleDB (pool size {})", cfg.db_pool_size);
 
-    // Create Kafka consumer
+    // Create Kafka consumer. Offsets are committed by hand, after the
+    // corresponding batch has been durably written to TimescaleDB, so a
+    // crash replays unprocessed events rather than skipping them.
     let consumer: StreamConsumer = ClientConfig::new()
-        .set("group.id", KAFKA_GROUP_ID)
-        .set("bootstrap.servers", KAFKA_BROKER)
-        .set("enable.auto.commit", "true")
+        .set("group.id", &cfg.kafka_group_id)
+        .set("bootstrap.servers", &cfg.kafka_broker)
+        .set("enable.auto.commit", "false")
         .set("auto.offset.reset", "earliest")
         .create()?;
 
-    consumer.subscribe(&[KAFKA_TOPIC])?;
-    info!("✅ Subscribed to Kafka topic: {}", KAFKA_TOPIC);
+    consumer.subscribe(&[cfg.kafka_topic.as_str()])?;
+    info!("✅ Subscribed to Kafka topic: {}", cfg.kafka_topic);
     info!("📡 Listening for events...");
 
+    // Producer for the enriched, priced-session output topic.
+    let kafka_producer = producer::build_producer(&cfg.kafka_broker)?;
+    info!("✅ Publishing enriched pricing events to: {}", cfg.kafka_output_topic);
+
     // Create HTTP client for fetching prices
     let http_client = reqwest::Client::new();
+    let price_oracle = build_oracle(http_client, &cfg.mock_grid_url);
+    let mut candle_aggregator = CandleAggregator::new();
+
+    let station_registry = StationRegistry::load(&cfg.stations_config_path)?;
+    let mut car_sessions = CarSessionTracker::new();
+
+    let latency_metrics = Arc::new(LatencyMetrics::new());
+    metrics::spawn_periodic_logger(latency_metrics.clone(), cfg.metrics_log_interval);
+    metrics::spawn_metrics_server(latency_metrics.clone(), cfg.metrics_addr.clone());
+
+    let mut batch: Vec<PendingEvent> = Vec::new();
+    let mut offsets: HashMap<i32, i64> = HashMap::new();
+    let mut last_flush = Instant::now();
+    // Set once a flush fails and batch_max_size stays tripped with
+    // TimescaleDB still down, so the size-triggered flush below doesn't
+    // retry on every single incoming message; cleared as soon as a flush
+    // succeeds.
+    let mut retry_after: Option<Instant> = None;
 
     // Consume messages
     loop {
-        match consumer.recv().await {
-            Ok(message) => {
+        let time_left = cfg.batch_max_interval.saturating_sub(last_flush.elapsed());
+
+        match tokio::time::timeout(time_left, consumer.recv()).await {
+            Ok(Ok(message)) => {
+                let partition = message.partition();
+                let offset = message.offset();
+
                 if let Some(payload) = message.payload() {
                     match std::str::from_utf8(payload) {
                         Ok(json_str) => {
@@ -78,8 +130,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                     event.car_id, event.battery, event.lat, event.lon
                                 );
 
-                                // Fetch current pricing from mock grid service
-                                match fetch_current_price(&http_client).await {
+                                let Some(station) = station_registry.nearest(event.lat, event.lon) else {
+                                    error!("No stations configured, dropping event for car {}", event.car_id);
+                                    offsets.insert(partition, offset);
+                                    continue;
+                                };
+                                let station_id = station.station_id.clone();
+
+                                // Model energy delivered since this car's last
+                                // event from the station's charger power; the
+                                // first event seen for a car has no prior
+                                // sample to measure a charging interval against.
+                                let kwh_usage = car_sessions
+                                    .elapsed_since_last(&event.car_id, event.timestamp)
+                                    .map(|elapsed_secs| station.max_power_kw * (elapsed_secs as f64 / 3600.0))
+                                    .unwrap_or(0.0);
+
+                                // Fetch pricing, falling back across providers. The
+                                // time-of-use and flat-rate providers price the hour
+                                // this event was produced in; the mock grid endpoint
+                                // returns its current price.
+                                match price_oracle.fetch(event.timestamp).await {
                                     Ok(price_info) => {
                                         info!(
                                             "💰 Price: ${:.3}/kWh | Load: {} | Source: {}",
@@ -88,18 +159,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                             price_info.energy_source
                                         );
 
-                                        // Write to TimescaleDB (simulate charging session)
-                                        if let Err(e) = write_to_timescale(
-                                            &client,
-                                            &event,
-                                            &price_info,
-                                        )
-                                        .await
-                                        {
-                                            error!("Failed to write to TimescaleDB: {}", e);
-                                        } else {
-                                            info!("✅ Written to TimescaleDB");
-                                        }
+                                        batch.push(PendingEvent {
+                                            event,
+                                            price_info,
+                                            station_id,
+                                            kwh_usage,
+                                        });
                                     }
                                     Err(e) => {
                                         error!("Failed to fetch pricing: {}", e);
@@ -112,57 +177,247 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         }
                     }
                 }
+
+                // Track the highest offset seen per partition even for
+                // messages we couldn't process, so the consumer doesn't
+                // get stuck retrying a message it will never be able to
+                // persist.
+                offsets.insert(partition, offset);
+
+                let size_triggered = batch.len() >= cfg.batch_max_size;
+                let backing_off = retry_after.is_some_and(|until| Instant::now() < until);
+
+                if size_triggered && !backing_off {
+                    let flushed = flush_batch(
+                        &pool,
+                        &consumer,
+                        &mut candle_aggregator,
+                        &latency_metrics,
+                        &kafka_producer,
+                        &cfg.kafka_output_topic,
+                        &cfg.kafka_topic,
+                        &mut batch,
+                        &mut offsets,
+                    )
+                    .await;
+                    last_flush = Instant::now();
+                    retry_after = (!flushed).then(|| Instant::now() + cfg.batch_max_interval);
+                }
             }
-            Err(e) => {
+            Ok(Err(e)) => {
                 error!("Kafka error: {}", e);
                 tokio::time::sleep(Duration::from_secs(1)).await;
             }
+            Err(_timeout) => {
+                let flushed = flush_batch(
+                    &pool,
+                    &consumer,
+                    &mut candle_aggregator,
+                    &latency_metrics,
+                    &kafka_producer,
+                    &cfg.kafka_output_topic,
+                    &cfg.kafka_topic,
+                    &mut batch,
+                    &mut offsets,
+                )
+                .await;
+                last_flush = Instant::now();
+                retry_after = (!flushed).then(|| Instant::now() + cfg.batch_max_interval);
+            }
+        }
+    }
+}
+
+/// Writes every pending event to TimescaleDB as a single multi-row
+/// INSERT inside one transaction, then commits the Kafka offsets only if
+/// that transaction commits - giving at-least-once delivery instead of
+/// silently losing sessions on a DB error, with one round-trip per batch
+/// rather than one per event. Returns whether the flush succeeded, so the
+/// caller can back off retries while TimescaleDB is unreachable instead
+/// of hammering it on every incoming message.
+#[allow(clippy::too_many_arguments)]
+async fn flush_batch(
+    pool: &DbPool,
+    consumer: &StreamConsumer,
+    candle_aggregator: &mut CandleAggregator,
+    latency_metrics: &LatencyMetrics,
+    kafka_producer: &FutureProducer,
+    output_topic: &str,
+    kafka_topic: &str,
+    batch: &mut Vec<PendingEvent>,
+    offsets: &mut HashMap<i32, i64>,
+) -> bool {
+    if batch.is_empty() {
+        offsets.clear();
+        return true;
+    }
+
+    let mut conn = match pool.get().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Failed to get a DB connection from the pool: {}", e);
+            return false;
+        }
+    };
+
+    let txn = match conn.transaction().await {
+        Ok(txn) => txn,
+        Err(e) => {
+            error!("Failed to start DB transaction: {}", e);
+            return false;
+        }
+    };
+
+    let rows = match write_batch_to_timescale(&txn, batch).await {
+        Ok(rows) => rows,
+        Err(e) => {
+            // txn is dropped here and rolled back. Leave `batch` and
+            // `offsets` untouched rather than clearing them: the events
+            // stay queued and get retried on the next flush, instead of
+            // being dropped while their Kafka offsets still advance past
+            // them.
+            error!(
+                "Failed to write batch to TimescaleDB, will retry on next flush: {}",
+                e
+            );
+            return false;
+        }
+    };
+
+    for (pending, row) in batch.iter().zip(rows.iter()) {
+        if let Err(e) = candle_aggregator
+            .ingest(
+                &txn,
+                pending.event.timestamp,
+                pending.price_info.price_per_kwh,
+                row.kwh_usage,
+            )
+            .await
+        {
+            error!("Failed to aggregate price candle: {}", e);
+        }
+    }
+
+    let event_timestamps: Vec<i64> = batch.iter().map(|pending| pending.event.timestamp).collect();
+
+    if let Err(e) = txn.commit().await {
+        // As with a failed write, keep `batch` queued for the next flush.
+        error!("Failed to commit DB transaction: {}", e);
+        return false;
+    }
+
+    let pending_events = std::mem::take(batch);
+
+    let written = pending_events.len();
+    let committed_at = Utc::now().timestamp_millis();
+    for event_timestamp in event_timestamps {
+        let latency_ms = (committed_at - event_timestamp * 1000).max(0) as u64;
+        latency_metrics.record_latency_ms(latency_ms);
+    }
+
+    info!("✅ Committed {} charging session(s) to TimescaleDB", written);
+
+    for (pending, row) in pending_events.iter().zip(rows.iter()) {
+        let enriched = PricedSessionEvent {
+            car_id: &pending.event.car_id,
+            lat: pending.event.lat,
+            lon: pending.event.lon,
+            battery: pending.event.battery,
+            price_per_kwh: pending.price_info.price_per_kwh,
+            grid_load: &pending.price_info.grid_load,
+            energy_source: &pending.price_info.energy_source,
+            session_id: &row.session_id,
+        };
+
+        if let Err(e) = producer::publish_priced_session(kafka_producer, output_topic, &enriched).await {
+            error!("Failed to publish enriched pricing event: {}", e);
         }
     }
+
+    let mut tpl = TopicPartitionList::new();
+    for (&partition, &offset) in offsets.iter() {
+        if let Err(e) = tpl.add_partition_offset(kafka_topic, partition, Offset::Offset(offset + 1)) {
+            error!("Failed to stage offset for partition {}: {}", partition, e);
+        }
+    }
+
+    if let Err(e) = consumer.commit(&tpl, CommitMode::Async) {
+        error!("Failed to commit Kafka offsets: {}", e);
+    }
+
+    offsets.clear();
+    true
 }
 
-async fn fetch_current_price(
-    client: &reqwest::Client,
-) -> Result<PriceResponse, Box<dyn std::error::Error>> {
-    let response = client
-        .get(MOCK_GRID_URL)
-        .timeout(Duration::from_secs(5))
-        .send()
-        .await?;
-
-    let price_info = response.json::<PriceResponse>().await?;
-    Ok(price_info)
+/// One row bound for the `charging_sessions` multi-VALUES insert.
+struct SessionRow {
+    time: DateTime<Utc>,
+    session_id: String,
+    station_id: String,
+    car_id: String,
+    kwh_usage: f64,
+    price_per_kwh: f64,
 }
 
-async fn write_to_timescale(
-    client: &tokio_postgres::Client,
-    event: &TelemetryEvent,
-    price_info: &PriceResponse,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Generate a session_id and station_id for demonstration
-    let session_id = format!("session-{}-{}", event.car_id, Utc::now().timestamp());
-    let station_id = format!("station-{}", (event.car_id.chars().last().unwrap() as u32) % 10);
-
-    // Simulate energy usage (random between 5-20 kWh)
-    let kwh_usage = 10.0 + (event.battery / 10.0);
-
-    // Use DateTime<Utc> instead of NaiveDateTime for TimescaleDB
-    let now = Utc::now();
-
-    client
-        .execute(
-            "INSERT INTO charging_sessions (time, session_id, station_id, car_id, kwh_usage, price_rate)
-             VALUES ($1, $2, $3, $4, $5, $6)",
-            &[
-                &now,
-                &session_id,
-                &station_id,
-                &event.car_id,
-                &kwh_usage,
-                &price_info.price_per_kwh,
-            ],
-        )
-        .await?;
-
-    Ok(())
+/// Inserts the whole batch as a single multi-row `INSERT`, returning the
+/// row bound for each event (in batch order) so the caller can feed it
+/// into candle aggregation and enrichment publishing without a second
+/// pass over the DB.
+async fn write_batch_to_timescale(
+    txn: &tokio_postgres::Transaction<'_>,
+    batch: &[PendingEvent],
+) -> Result<Vec<SessionRow>, Box<dyn std::error::Error>> {
+    // Each row gets its own time derived from the event it came from,
+    // rather than one `now` shared across the whole batch - a car that
+    // emits more than one event within a single flush window would
+    // otherwise collapse onto the same `time` and `session_id`.
+    let rows: Vec<SessionRow> = batch
+        .iter()
+        .map(|pending| {
+            let event = &pending.event;
+            let time = Utc
+                .timestamp_opt(event.timestamp, 0)
+                .single()
+                .unwrap_or_else(Utc::now);
+            SessionRow {
+                time,
+                session_id: format!("session-{}-{}", event.car_id, event.timestamp),
+                station_id: pending.station_id.clone(),
+                car_id: event.car_id.clone(),
+                kwh_usage: pending.kwh_usage,
+                price_per_kwh: pending.price_info.price_per_kwh,
+            }
+        })
+        .collect();
+
+    let mut query = String::from(
+        "INSERT INTO charging_sessions (time, session_id, station_id, car_id, kwh_usage, price_rate) VALUES ",
+    );
+    let mut params: Vec<&(dyn ToSql + Sync)> = Vec::with_capacity(rows.len() * 6);
+
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            query.push_str(", ");
+        }
+        let base = i * 6;
+        query.push_str(&format!(
+            "(${}, ${}, ${}, ${}, ${}, ${})",
+            base + 1,
+            base + 2,
+            base + 3,
+            base + 4,
+            base + 5,
+            base + 6
+        ));
+        params.push(&row.time);
+        params.push(&row.session_id);
+        params.push(&row.station_id);
+        params.push(&row.car_id);
+        params.push(&row.kwh_usage);
+        params.push(&row.price_per_kwh);
+    }
+
+    txn.execute(query.as_str(), &params).await?;
+
+    Ok(rows)
 }
@@ -0,0 +1,115 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use hdrhistogram::Histogram;
+use log::{error, info};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+
+/// Tracks end-to-end latency (telemetry produced -> row committed to
+/// TimescaleDB) and throughput for the pricing worker. Backed by an HDR
+/// histogram so percentiles stay cheap to compute under load.
+pub struct LatencyMetrics {
+    histogram: Mutex<Histogram<u64>>,
+    processed: AtomicU64,
+}
+
+impl LatencyMetrics {
+    pub fn new() -> Self {
+        Self {
+            histogram: Mutex::new(Histogram::new(3).expect("valid histogram sigfigs")),
+            processed: AtomicU64::new(0),
+        }
+    }
+
+    /// Records the latency, in milliseconds, between an event's
+    /// `timestamp` and the moment its row was committed to TimescaleDB.
+    pub fn record_latency_ms(&self, latency_ms: u64) {
+        if let Ok(mut histogram) = self.histogram.lock() {
+            let _ = histogram.record(latency_ms);
+        }
+        self.processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn processed_count(&self) -> u64 {
+        self.processed.load(Ordering::Relaxed)
+    }
+
+    pub fn summary(&self) -> String {
+        let histogram = self.histogram.lock().unwrap();
+        format!(
+            "p50={}ms p95={}ms p99={}ms max={}ms count={}",
+            histogram.value_at_quantile(0.50),
+            histogram.value_at_quantile(0.95),
+            histogram.value_at_quantile(0.99),
+            histogram.max(),
+            histogram.len(),
+        )
+    }
+}
+
+impl Default for LatencyMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Logs p50/p95/p99/max latency and throughput on a fixed interval, so
+/// operators can see when the worker falls behind the fleet-events topic.
+pub fn spawn_periodic_logger(metrics: Arc<LatencyMetrics>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        let mut last_count = 0u64;
+
+        loop {
+            ticker.tick().await;
+
+            let count = metrics.processed_count();
+            let throughput = (count - last_count) as f64 / interval.as_secs_f64();
+            info!("📊 Latency {} | throughput={:.1} events/s", metrics.summary(), throughput);
+            last_count = count;
+        }
+    });
+}
+
+/// Serves a minimal `/metrics` endpoint with the current latency summary
+/// as plain text, so operators can scrape it without tailing logs.
+pub fn spawn_metrics_server(metrics: Arc<LatencyMetrics>, addr: String) {
+    tokio::spawn(async move {
+        let listener = match TcpListener::bind(&addr).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to bind metrics endpoint on {}: {}", addr, e);
+                return;
+            }
+        };
+
+        info!("📈 Metrics endpoint listening on http://{}/metrics", addr);
+
+        loop {
+            let (mut socket, _) = match listener.accept().await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    error!("Failed to accept metrics connection: {}", e);
+                    continue;
+                }
+            };
+
+            let body = format!(
+                "{}\nprocessed_total {}\n",
+                metrics.summary(),
+                metrics.processed_count()
+            );
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+
+            if let Err(e) = socket.write_all(response.as_bytes()).await {
+                error!("Failed to write metrics response: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,207 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Default mock grid endpoint, used only when `MockGridOracle` is built
+/// via `Default` rather than `new`.
+const DEFAULT_MOCK_GRID_URL: &str = "http://localhost:8081/api/pricing";
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct PriceResponse {
+    pub timestamp: i64,
+    pub price_per_kwh: f64,
+    pub grid_load: String,
+    pub energy_source: String,
+    pub hour: i32,
+}
+
+/// A source of current grid pricing. Implementations may hit an external
+/// service, read a static configuration, or derive a price locally -
+/// `FallbackOracle` lets several of these be composed so a single outage
+/// doesn't take down the charging session.
+#[async_trait]
+pub trait PricingOracle: Send + Sync {
+    /// Resolves a price for `event_timestamp` (unix seconds) - the time the
+    /// telemetry event was produced, not the time it's being processed, so
+    /// a backlog replay prices events for the hour they actually happened
+    /// in rather than whatever hour the worker caught up during.
+    async fn fetch(&self, event_timestamp: i64) -> Result<PriceResponse, Box<dyn std::error::Error>>;
+
+    /// Short name used in logs when an oracle in a fallback chain fails.
+    fn name(&self) -> &'static str;
+}
+
+/// Queries the mock grid HTTP endpoint used in local/dev environments.
+/// The request carries no time, so the price returned is the endpoint's
+/// current one; `event_timestamp` is not used.
+pub struct MockGridOracle {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl MockGridOracle {
+    pub fn new(client: reqwest::Client, url: impl Into<String>) -> Self {
+        Self {
+            client,
+            url: url.into(),
+        }
+    }
+}
+
+impl Default for MockGridOracle {
+    fn default() -> Self {
+        Self::new(reqwest::Client::new(), DEFAULT_MOCK_GRID_URL)
+    }
+}
+
+#[async_trait]
+impl PricingOracle for MockGridOracle {
+    async fn fetch(&self, _event_timestamp: i64) -> Result<PriceResponse, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .get(&self.url)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await?;
+
+        let price_info = response.json::<PriceResponse>().await?;
+        Ok(price_info)
+    }
+
+    fn name(&self) -> &'static str {
+        "mock-grid"
+    }
+}
+
+/// Falls back to a fixed, operator-configured rate (e.g. via
+/// `FLAT_RATE_PRICE_PER_KWH`) when no live pricing is available.
+pub struct FlatRateOracle {
+    price_per_kwh: f64,
+}
+
+impl FlatRateOracle {
+    pub fn new(price_per_kwh: f64) -> Self {
+        Self { price_per_kwh }
+    }
+
+    /// Builds a `FlatRateOracle` from the `FLAT_RATE_PRICE_PER_KWH` env var,
+    /// defaulting to `default_price` if it isn't set or doesn't parse.
+    pub fn from_env(default_price: f64) -> Self {
+        let price_per_kwh = std::env::var("FLAT_RATE_PRICE_PER_KWH")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default_price);
+        Self::new(price_per_kwh)
+    }
+}
+
+#[async_trait]
+impl PricingOracle for FlatRateOracle {
+    async fn fetch(&self, event_timestamp: i64) -> Result<PriceResponse, Box<dyn std::error::Error>> {
+        let hour = Utc
+            .timestamp_opt(event_timestamp, 0)
+            .single()
+            .map(|dt| dt.hour() as i32)
+            .unwrap_or(0);
+
+        Ok(PriceResponse {
+            timestamp: event_timestamp,
+            price_per_kwh: self.price_per_kwh,
+            grid_load: "unknown".to_string(),
+            energy_source: "flat-rate".to_string(),
+            hour,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "flat-rate"
+    }
+}
+
+/// Derives a price locally from a simple time-of-use schedule, so that
+/// a pricing session can still reflect peak/off-peak load even without
+/// reaching any upstream service.
+pub struct TimeOfUseOracle;
+
+impl TimeOfUseOracle {
+    pub fn new() -> Self {
+        Self
+    }
+
+    fn rate_for_hour(hour: u32) -> (f64, &'static str) {
+        match hour {
+            0..=5 => (0.08, "off-peak"),
+            6..=15 => (0.14, "mid-peak"),
+            16..=20 => (0.26, "peak"),
+            _ => (0.14, "mid-peak"),
+        }
+    }
+}
+
+impl Default for TimeOfUseOracle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl PricingOracle for TimeOfUseOracle {
+    async fn fetch(&self, event_timestamp: i64) -> Result<PriceResponse, Box<dyn std::error::Error>> {
+        let hour = Utc
+            .timestamp_opt(event_timestamp, 0)
+            .single()
+            .map(|dt| dt.hour())
+            .unwrap_or(0);
+        let (price_per_kwh, grid_load) = Self::rate_for_hour(hour);
+
+        Ok(PriceResponse {
+            timestamp: event_timestamp,
+            price_per_kwh,
+            grid_load: grid_load.to_string(),
+            energy_source: "time-of-use".to_string(),
+            hour: hour as i32,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        "time-of-use"
+    }
+}
+
+/// Tries each oracle in order, returning the first successful price. Use
+/// this to degrade gracefully from a live grid API down to a local
+/// estimate instead of dropping the charging session entirely.
+pub struct FallbackOracle {
+    oracles: Vec<Box<dyn PricingOracle>>,
+}
+
+impl FallbackOracle {
+    pub fn new(oracles: Vec<Box<dyn PricingOracle>>) -> Self {
+        Self { oracles }
+    }
+}
+
+#[async_trait]
+impl PricingOracle for FallbackOracle {
+    async fn fetch(&self, event_timestamp: i64) -> Result<PriceResponse, Box<dyn std::error::Error>> {
+        let mut last_err: Option<Box<dyn std::error::Error>> = None;
+
+        for oracle in &self.oracles {
+            match oracle.fetch(event_timestamp).await {
+                Ok(price) => return Ok(price),
+                Err(e) => {
+                    log::warn!("Oracle '{}' failed, trying next: {}", oracle.name(), e);
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        Err(last_err.unwrap_or_else(|| "no pricing oracles configured".into()))
+    }
+
+    fn name(&self) -> &'static str {
+        "fallback"
+    }
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use rdkafka::config::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::util::Timeout;
+use serde::Serialize;
+
+/// Enriched pricing record published once a session has been persisted,
+/// so downstream services can consume priced sessions without querying
+/// the database directly.
+#[derive(Debug, Serialize)]
+pub struct PricedSessionEvent<'a> {
+    pub car_id: &'a str,
+    pub lat: f64,
+    pub lon: f64,
+    pub battery: f64,
+    pub price_per_kwh: f64,
+    pub grid_load: &'a str,
+    pub energy_source: &'a str,
+    pub session_id: &'a str,
+}
+
+pub fn build_producer(broker: &str) -> Result<FutureProducer, Box<dyn std::error::Error>> {
+    let producer: FutureProducer = ClientConfig::new()
+        .set("bootstrap.servers", broker)
+        .set("message.timeout.ms", "5000")
+        .create()?;
+    Ok(producer)
+}
+
+/// Publishes an enriched pricing event, keyed by `car_id` for partition
+/// affinity so all of a car's priced sessions land on the same partition.
+pub async fn publish_priced_session(
+    producer: &FutureProducer,
+    topic: &str,
+    event: &PricedSessionEvent<'_>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let payload = serde_json::to_string(event)?;
+
+    producer
+        .send(
+            FutureRecord::to(topic).key(event.car_id).payload(&payload),
+            Timeout::After(Duration::from_secs(5)),
+        )
+        .await
+        .map_err(|(e, _)| e)?;
+
+    Ok(())
+}
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// A single charging station, as loaded from `stations.json`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Station {
+    pub station_id: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub max_power_kw: f64,
+    pub connector_type: String,
+}
+
+/// Station locations and specs, loaded once at startup so telemetry
+/// events can be assigned to the physical station nearest their
+/// reported coordinates.
+pub struct StationRegistry {
+    stations: Vec<Station>,
+}
+
+impl StationRegistry {
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let data = std::fs::read_to_string(path)?;
+        let stations: Vec<Station> = serde_json::from_str(&data)?;
+        Ok(Self { stations })
+    }
+
+    /// Finds the station nearest to `(lat, lon)` by straight-line
+    /// distance. Good enough at city scale without pulling in a geodesic
+    /// distance crate.
+    pub fn nearest(&self, lat: f64, lon: f64) -> Option<&Station> {
+        self.stations
+            .iter()
+            .min_by(|a, b| distance_sq(a, lat, lon).total_cmp(&distance_sq(b, lat, lon)))
+    }
+}
+
+fn distance_sq(station: &Station, lat: f64, lon: f64) -> f64 {
+    let dlat = station.lat - lat;
+    let dlon = station.lon - lon;
+    dlat * dlat + dlon * dlon
+}
+
+/// Tracks, per car, the timestamp of its most recently seen event so
+/// `kwh_usage` can be modeled from elapsed charging time rather than a
+/// fabricated formula.
+pub struct CarSessionTracker {
+    last_seen: HashMap<String, i64>,
+}
+
+impl CarSessionTracker {
+    pub fn new() -> Self {
+        Self {
+            last_seen: HashMap::new(),
+        }
+    }
+
+    /// Returns the elapsed seconds since this car's last event, updating
+    /// the tracked timestamp. `None` the first time a car is seen, since
+    /// there's no prior sample to measure a charging interval against.
+    pub fn elapsed_since_last(&mut self, car_id: &str, timestamp: i64) -> Option<i64> {
+        let elapsed = self
+            .last_seen
+            .get(car_id)
+            .map(|&previous| (timestamp - previous).max(0));
+        self.last_seen.insert(car_id.to_string(), timestamp);
+        elapsed
+    }
+}
+
+impl Default for CarSessionTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}